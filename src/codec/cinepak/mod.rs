@@ -1,4 +1,5 @@
-use utils::uint16_from_bytes;
+use error::FilmError;
+use utils::{uint16_from_bytes, uint32_from_bytes, Reader};
 
 // Referenced from https://multimedia.cx/mirror/cinepak.txt, and
 // the FFmpeg source.
@@ -16,37 +17,87 @@ pub struct Frame {
 }
 
 impl Frame {
-    pub fn parse(data : &[u8]) -> Frame {
-        let strip_count = uint16_from_bytes([data[8], data[9]]) as usize;
+    pub fn parse(data : &[u8]) -> Result<Frame, FilmError> {
+        let mut reader = Reader::new(data);
+
+        // The 12-byte frame header precedes the strips.
+        let _flags = reader.read_u32()?;
+        let height = reader.read_u16()? as usize;
+        let width = reader.read_u16()? as usize;
+        let strip_count = reader.read_u16()? as usize;
+        reader.seek(STRIP_START_OFFSET)?;
 
         let mut strips = vec![];
         // Strips can be relative in position to previous strips;
         // this value is kept throughout the loop to refer back to.
         let mut prev_y2 = 0;
-        let mut current_offset : usize = 0;
 
-        for i in 0..strip_count {
-            let start_index = STRIP_START_OFFSET + current_offset;
-            let strip_size = Strip::parse_strip_size(&data[start_index..start_index + 4]);
+        for _ in 0..strip_count {
+            let start_index = reader.position();
+            // The strip size lives in its own header; a strip must be at least
+            // the 12-byte header long and must fit in the remaining buffer, so a
+            // lying size can't slice out of bounds.
+            let size_field = reader.read_bytes(4)?;
+            let strip_size = Strip::parse_strip_size(size_field);
+            if strip_size < STRIP_START_OFFSET || start_index + strip_size > data.len() {
+                return Err(FilmError::UnexpectedEof);
+            }
 
             let strip_data = &data[start_index..start_index + strip_size];
-            strips.push(Strip::parse(strip_data, prev_y2));
+            let strip = Strip::parse(strip_data, prev_y2)?;
+            prev_y2 = strip.y2;
+            strips.push(strip);
 
-            prev_y2 = strips[i].y2;
-            current_offset += strip_size;
+            reader.seek(start_index + strip_size)?;
         }
 
         // TODO: This is also available at the sample level;
         //       is that value more accurate than this?
         let is_keyframe = strips.iter().any(|strip| strip.id == 0x10);
 
-        return Frame {
-            width: uint16_from_bytes([data[6], data[7]]) as usize,
-            height: uint16_from_bytes([data[4], data[5]]) as usize,
+        return Ok(Frame {
+            width: width,
+            height: height,
             keyframe: is_keyframe,
             strip_count: strip_count,
             strips: strips,
+        });
+    }
+
+    /// Decodes the Cinepak chunk stream in every strip into a packed RGB buffer.
+    /// The result is `width * height * 3` bytes, three bytes (R, G, B) per pixel
+    /// in raster order.
+    ///
+    /// Decoding is stateful: Cinepak's inter frames update only part of the
+    /// image and refer back to the previous frame's codebooks, so the caller
+    /// threads both across frames. Pass the previously decoded image as `previous`
+    /// (or `None` for the first frame / a standalone keyframe) and the two
+    /// codebooks, which this call mutates in place. Create a starting pair with
+    /// [`Frame::empty_codebooks`].
+    ///
+    /// Blocks skipped on a `0x31` inter chunk keep the pixels copied from
+    /// `previous`, and partial-codebook updates (`0x21`/`0x23`) build on the
+    /// vectors the codebooks already hold.
+    pub fn decode(&self, previous : Option<&[u8]>, v1_codebook : &mut [Vector], v4_codebook : &mut [Vector]) -> Vec<u8> {
+        let size = self.width * self.height * 3;
+        // Seed from the previous frame so skipped blocks keep their content;
+        // fall back to black when there's no prior frame or it's the wrong size.
+        let mut image = match previous {
+            Some(prev) if prev.len() == size => prev.to_vec(),
+            _ => vec![0u8; size],
+        };
+
+        for strip in &self.strips {
+            strip.decode_into(&mut image, self.width, v1_codebook, v4_codebook);
         }
+
+        return image;
+    }
+
+    /// Returns a fresh pair of empty `(v1, v4)` codebooks, each holding 256
+    /// default vectors, ready to thread through [`Frame::decode`].
+    pub fn empty_codebooks() -> (Vec<Vector>, Vec<Vector>) {
+        return (vec![Vector::default(); 256], vec![Vector::default(); 256]);
     }
 }
 
@@ -61,7 +112,11 @@ pub struct Strip {
 }
 
 impl Strip {
-    pub fn parse(data : &[u8], prev_y2 : usize) -> Strip {
+    pub fn parse(data : &[u8], prev_y2 : usize) -> Result<Strip, FilmError> {
+        if data.len() < STRIP_START_OFFSET {
+            return Err(FilmError::UnexpectedEof);
+        }
+
         let y1;
         let y2;
         // 0 means relative to the previous strip
@@ -81,7 +136,7 @@ impl Strip {
         debug_assert!(header.len() == 12);
         debug_assert!((header.len() + strip_data.len()) == data.len());
 
-        return Strip {
+        return Ok(Strip {
             id: uint16_from_bytes([data[0], data[1]]),
             x1: uint16_from_bytes([data[6], data[7]]) as usize,
             x2: uint16_from_bytes([data[10], data[11]]) as usize,
@@ -89,15 +144,314 @@ impl Strip {
             y2: y2,
             header: header,
             data: strip_data,
-        }
+        });
     }
 
     pub fn parse_strip_size(data : &[u8]) -> usize {
         // TODO: this might sometimes overshoot?
         return uint16_from_bytes([data[2], data[3]]) as usize;
     }
+
+    /// Decodes this strip's chunk stream into `image`, a packed RGB buffer whose
+    /// rows are `width` pixels wide. The codebooks are shared with the rest of
+    /// the frame so that partial-update chunks can build on earlier vectors.
+    fn decode_into(&self, image : &mut [u8], width : usize, v1_codebook : &mut [Vector], v4_codebook : &mut [Vector]) {
+        let data = &self.data;
+        let mut cursor = 0;
+
+        while cursor + 4 <= data.len() {
+            let id = uint16_from_bytes([data[cursor], data[cursor + 1]]);
+            let size = uint16_from_bytes([data[cursor + 2], data[cursor + 3]]) as usize;
+            // A zero-length chunk would never advance the cursor; bail out rather
+            // than spin forever on a malformed stream.
+            if size < 4 {
+                break;
+            }
+
+            let end = if cursor + size > data.len() { data.len() } else { cursor + size };
+            let body = &data[cursor + 4..end];
+
+            match id {
+                // Codebook chunks (0x20..=0x27). The chunk id alone determines
+                // the layout: bit 0x02 picks the V1 rather than the V4 codebook,
+                // bit 0x01 marks a partial update, and bit 0x04 marks grayscale
+                // (4-byte) rather than full YUV (6-byte) vectors. Entry width must
+                // come from the id, never the body length.
+                0x20..=0x27 => {
+                    let entry_size = if id & 0x04 != 0 { 4 } else { 6 };
+                    if id & 0x02 != 0 {
+                        Strip::load_codebook(v1_codebook, body, entry_size, id & 0x01 != 0);
+                    } else {
+                        Strip::load_codebook(v4_codebook, body, entry_size, id & 0x01 != 0);
+                    }
+                }
+                0x30 | 0x31 | 0x32 => self.decode_vectors(id, body, image, width, v1_codebook, v4_codebook),
+                _ => {}
+            }
+
+            cursor += size;
+        }
+    }
+
+    /// Loads a codebook from a chunk body. `entry_size` is the per-vector width
+    /// (6 bytes for full YUV vectors, 4 for grayscale), dictated by the chunk id.
+    ///
+    /// A full update replaces every vector in sequence. A partial update instead
+    /// interleaves 32-bit flag words with the vectors: each word governs the next
+    /// 32 vectors, where a set bit means the corresponding vector is present and
+    /// should be read, while a clear bit leaves that vector untouched.
+    fn load_codebook(codebook : &mut [Vector], body : &[u8], entry_size : usize, partial : bool) {
+        let mut flags = Bitstream::new(body);
+        for vector in codebook.iter_mut() {
+            if partial && !flags.get_flag() {
+                continue;
+            }
+            match flags.take_bytes(entry_size) {
+                Some(entry) => *vector = Vector::parse(entry),
+                None => break,
+            }
+        }
+    }
+
+    /// Decodes a block of coded vectors into the image buffer.
+    ///
+    /// `id` distinguishes the three coded-block chunk types: `0x31` carries a skip
+    /// flag per macroblock (used by inter frames), and `0x32` forces every block
+    /// to V4; otherwise a flag bit selects V1 versus V4 for each macroblock.
+    fn decode_vectors(&self, id : u16, body : &[u8], image : &mut [u8], width : usize, v1_codebook : &[Vector], v4_codebook : &[Vector]) {
+        let has_skip_flags = (id & 0x01) != 0;
+        let always_v4 = (id & 0x02) != 0;
+        let mut stream = Bitstream::new(body);
+
+        let mut y = self.y1;
+        while y + 4 <= self.y2 {
+            let mut x = self.x1;
+            while x + 4 <= self.x2 {
+                // Inter frames prefix each macroblock with a bit; a clear bit
+                // means the block is unchanged from the previous frame.
+                if has_skip_flags && !stream.get_flag() {
+                    x += 4;
+                    continue;
+                }
+
+                let is_v4 = always_v4 || stream.get_flag();
+                if is_v4 {
+                    let mut vectors = [Vector::default(); 4];
+                    for vector in vectors.iter_mut() {
+                        match stream.take_byte() {
+                            Some(index) => *vector = v4_codebook[index as usize],
+                            None => return,
+                        }
+                    }
+                    paint_v4(image, width, x, y, &vectors);
+                } else {
+                    match stream.take_byte() {
+                        Some(index) => paint_v1(image, width, x, y, &v1_codebook[index as usize]),
+                        None => return,
+                    }
+                }
+
+                x += 4;
+            }
+            y += 4;
+        }
+    }
 }
 
+/// A Cinepak codebook vector: four luma samples arranged as a 2×2 block plus a
+/// shared, signed chroma pair. Grayscale vectors leave the chroma at zero.
+#[derive(Clone, Copy, Default)]
 pub struct Vector {
+    /// The four luma samples, in raster order (top-left, top-right, bottom-left,
+    /// bottom-right).
+    pub luma: [u8; 4],
+    /// The signed U (blue-difference) chroma sample.
+    pub u: i8,
+    /// The signed V (red-difference) chroma sample.
+    pub v: i8,
+}
+
+impl Vector {
+    /// Parses a codebook vector from its on-disk form. Six bytes carry luma and
+    /// chroma; the shorter four-byte form is luma-only, leaving the chroma zero.
+    fn parse(data : &[u8]) -> Vector {
+        let mut vector = Vector::default();
+        vector.luma = [data[0], data[1], data[2], data[3]];
+        if data.len() >= 6 {
+            vector.u = data[4] as i8;
+            vector.v = data[5] as i8;
+        }
+        return vector;
+    }
+}
+
+/// A big-endian, MSB-first bit reader layered over the same byte stream the
+/// codebook indices are read from. Cinepak interleaves flag words and index
+/// bytes within a single chunk, so both the bit reads and the byte reads draw
+/// from this one cursor.
+struct Bitstream<'a> {
+    data: &'a [u8],
+    pos: usize,
+    word: u32,
+    mask: u32,
+}
+
+impl<'a> Bitstream<'a> {
+    fn new(data : &'a [u8]) -> Bitstream<'a> {
+        return Bitstream { data: data, pos: 0, word: 0, mask: 0 };
+    }
+
+    /// Reads a single flag bit, pulling a fresh 32-bit word from the stream when
+    /// the current one is exhausted. Missing bytes read as zero.
+    fn get_flag(&mut self) -> bool {
+        if self.mask == 0 {
+            let mut word = [0u8; 4];
+            for byte in word.iter_mut() {
+                if self.pos < self.data.len() {
+                    *byte = self.data[self.pos];
+                    self.pos += 1;
+                }
+            }
+            self.word = uint32_from_bytes(word);
+            self.mask = 1 << 31;
+        }
 
+        let bit = (self.word & self.mask) != 0;
+        self.mask >>= 1;
+        return bit;
+    }
+
+    /// Reads a single byte (a codebook index) from the stream.
+    fn take_byte(&mut self) -> Option<u8> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let byte = self.data[self.pos];
+        self.pos += 1;
+        return Some(byte);
+    }
+
+    /// Reads `count` contiguous bytes, or `None` if the stream runs short.
+    fn take_bytes(&mut self, count : usize) -> Option<&'a [u8]> {
+        if self.pos + count > self.data.len() {
+            return None;
+        }
+        let slice = &self.data[self.pos..self.pos + count];
+        self.pos += count;
+        return Some(slice);
+    }
+}
+
+/// Converts a single YUV sample to RGB and writes it into the buffer at
+/// `(x, y)`. The chroma is signed; the result is clamped to `0..=255`.
+fn put_pixel(image : &mut [u8], width : usize, x : usize, y : usize, luma : u8, u : i8, v : i8) {
+    let offset = (y * width + x) * 3;
+    if offset + 3 > image.len() {
+        return;
+    }
+
+    let luma = luma as i32;
+    let u = u as i32;
+    let v = v as i32;
+    let r = luma + 2 * v;
+    let g = luma - (u / 2) - v;
+    let b = luma + 2 * u;
+
+    image[offset] = clamp(r);
+    image[offset + 1] = clamp(g);
+    image[offset + 2] = clamp(b);
+}
+
+fn clamp(value : i32) -> u8 {
+    if value < 0 {
+        return 0;
+    }
+    if value > 255 {
+        return 255;
+    }
+    return value as u8;
+}
+
+/// Paints a V1 macroblock: a single vector whose 2×2 luma is upsampled so that
+/// each sample fills a 2×2 corner of the 4×4 macroblock.
+fn paint_v1(image : &mut [u8], width : usize, x : usize, y : usize, vector : &Vector) {
+    for row in 0..4 {
+        for col in 0..4 {
+            // Pick the luma sample for this quadrant of the 4×4 block.
+            let index = (row / 2) * 2 + (col / 2);
+            put_pixel(image, width, x + col, y + row, vector.luma[index], vector.u, vector.v);
+        }
+    }
+}
+
+/// Paints a V4 macroblock: four vectors, one per 2×2 quadrant, each contributing
+/// its luma samples directly with no upsampling.
+fn paint_v4(image : &mut [u8], width : usize, x : usize, y : usize, vectors : &[Vector; 4]) {
+    for row in 0..4 {
+        for col in 0..4 {
+            // Vectors are ordered top-left, top-right, bottom-left, bottom-right.
+            let vector = &vectors[(row / 2) * 2 + (col / 2)];
+            // Within a quadrant, the luma samples are laid out the same way.
+            let index = (row % 2) * 2 + (col % 2);
+            put_pixel(image, width, x + col, y + row, vector.luma[index], vector.u, vector.v);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(image : &[u8], width : usize, x : usize, y : usize) -> (u8, u8, u8) {
+        let offset = (y * width + x) * 3;
+        return (image[offset], image[offset + 1], image[offset + 2]);
+    }
+
+    #[test]
+    fn paint_v1_upsamples_luma_into_quadrants() {
+        let width = 4;
+        let mut image = vec![0u8; width * 4 * 3];
+        let vector = Vector { luma: [10, 20, 30, 40], u: 0, v: 0 };
+        paint_v1(&mut image, width, 0, 0, &vector);
+
+        // Each luma sample fills one 2x2 corner of the 4x4 block.
+        assert_eq!(pixel(&image, width, 0, 0), (10, 10, 10));
+        assert_eq!(pixel(&image, width, 1, 1), (10, 10, 10));
+        assert_eq!(pixel(&image, width, 2, 0), (20, 20, 20));
+        assert_eq!(pixel(&image, width, 0, 2), (30, 30, 30));
+        assert_eq!(pixel(&image, width, 3, 3), (40, 40, 40));
+    }
+
+    #[test]
+    fn paint_v4_places_one_vector_per_quadrant() {
+        let width = 4;
+        let mut image = vec![0u8; width * 4 * 3];
+        let vectors = [
+            Vector { luma: [1, 2, 3, 4], u: 0, v: 0 },
+            Vector { luma: [5, 6, 7, 8], u: 0, v: 0 },
+            Vector { luma: [9, 10, 11, 12], u: 0, v: 0 },
+            Vector { luma: [13, 14, 15, 16], u: 0, v: 0 },
+        ];
+        paint_v4(&mut image, width, 0, 0, &vectors);
+
+        // Top-left quadrant comes from vector 0, laid out in raster order.
+        assert_eq!(pixel(&image, width, 0, 0), (1, 1, 1));
+        assert_eq!(pixel(&image, width, 1, 0), (2, 2, 2));
+        assert_eq!(pixel(&image, width, 0, 1), (3, 3, 3));
+        assert_eq!(pixel(&image, width, 1, 1), (4, 4, 4));
+        // One probe into each of the other three quadrants.
+        assert_eq!(pixel(&image, width, 2, 0), (5, 5, 5));
+        assert_eq!(pixel(&image, width, 0, 2), (9, 9, 9));
+        assert_eq!(pixel(&image, width, 2, 2), (13, 13, 13));
+    }
+
+    #[test]
+    fn put_pixel_clamps_chroma() {
+        let width = 1;
+        let mut image = vec![0u8; 3];
+        // r = y + 2v = 200 + 254 = 454 -> clamped to 255; b = y + 2u underflows to 0.
+        put_pixel(&mut image, width, 0, 0, 200, -128, 127);
+        assert_eq!(image[0], 255);
+        assert_eq!(image[2], 0);
+    }
 }
@@ -0,0 +1,7 @@
+//! This module contains the codecs used by the streams inside a FILM container.
+//! Cinepak is the only video codec decoded here; audio is either raw PCM or CRI ADX.
+
+/// The Cinepak video decoder.
+pub mod cinepak;
+/// The CRI ADX audio decoder.
+pub mod adx;
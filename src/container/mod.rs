@@ -4,8 +4,21 @@
 //! * FDSC, the format descriptor, which contains information about the container's contents
 //! * STAB, the sample table, which contains information about each sample within the container
 
-use utils::{uint16_from_bytes, uint32_from_bytes};
+use error::FilmError;
+use utils::{bytes_from_uint16, bytes_from_uint32, uint32_from_bytes, Reader};
 
+/// Serializes a text field to exactly `width` bytes, truncating an over-long
+/// value and zero-padding a short one. The FILM layout gives the signature,
+/// version, and fourcc fixed widths, so an off-size `String` must not be allowed
+/// to shift the bytes that follow it.
+fn fixed_field(value : &str, width : usize) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.truncate(width);
+    bytes.resize(width, 0);
+    return bytes;
+}
+
+#[derive(Clone)]
 pub enum AudioCodec {
     PCM,
     ADX,
@@ -22,6 +35,42 @@ impl AudioCodec {
             Unknown => "unknown"
         }
     }
+
+    /// Returns the byte stored in the FDSC to identify this codec.
+    /// This is the inverse of the mapping used when parsing an FDSC, except that
+    /// `Unknown` is written as 0, the PCM code.
+    pub fn code(&self) -> u8 {
+        use self::AudioCodec::*;
+
+        match *self {
+            PCM => 0,
+            ADX => 2,
+            Unknown => 0,
+        }
+    }
+}
+
+/// Identifies which of the container's two streams a sample belongs to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Stream {
+    /// The audio stream.
+    Audio,
+    /// The video stream.
+    Video,
+}
+
+/// One entry in a STAB [`timeline`](STAB::timeline): a sample placed on the
+/// container's shared tick clock. Times are expressed in ticks; divide by the
+/// STAB framerate to convert to seconds.
+pub struct TimelineEntry {
+    /// Which stream this entry belongs to.
+    pub stream: Stream,
+    /// The start time of this entry, in ticks from the beginning of playback.
+    pub start_time: u32,
+    /// The duration of this entry, in ticks.
+    pub duration: u32,
+    /// Whether this entry is a keyframe. Always `false` for audio.
+    pub keyframe: bool,
 }
 
 /// Represents the header of a FILM container.
@@ -34,7 +83,7 @@ impl AudioCodec {
 /// calculate how many bytes you'll need to read in order to parse the header.
 /// For example:
 ///
-/// ```
+/// ```ignore
 /// let file = File::open("myfile.cpk")?;
 /// let mut buf = vec![];
 /// // Start with only 8 bytes, so we don't waste memory
@@ -80,6 +129,9 @@ impl FILMHeader {
     /// This doesn't guarantee that the passed data actually represents a FILM file;
     /// if it doesn't, the guess will not be meaningful.
     pub fn guess_length(data : &[u8]) -> usize {
+        if data.len() < 8 {
+            return 0;
+        }
         return uint32_from_bytes([data[4], data[5], data[6], data[7]]) as usize;
     }
 
@@ -87,29 +139,56 @@ impl FILMHeader {
     /// `data` is a slice which is assumed to contain the beginning portion of
     /// a FILM file; it must contain at least the first 4 bytes of data.
     pub fn is_film_file(data : &[u8]) -> bool {
-        let signature = String::from_utf8(data[0..4].to_vec()).unwrap();
-        return signature == "FILM";
+        return data.len() >= 4 && &data[0..4] == b"FILM";
     }
 
     /// Parses the passed slice of bytes, returning a `FILMHeader` object.
     ///
-    /// If the supplied data doesn't appear to contain a FILM file, returns `Err`.
-    pub fn parse(data : &[u8]) -> Result<FILMHeader, &'static str> {
-        let signature = String::from_utf8(data[0..4].to_vec()).unwrap();
+    /// Returns `Err` if the data doesn't start with a FILM signature or is too
+    /// short to contain a complete header.
+    pub fn parse(data : &[u8]) -> Result<FILMHeader, FilmError> {
+        let mut reader = Reader::new(data);
+
+        let signature = reader.read_string(4)?;
         if signature != "FILM" {
-            return Err("This is not a Sega FILM file!");
+            return Err(FilmError::BadSignature);
         }
-        let length = uint32_from_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let length = reader.read_u32()? as usize;
+        let version = reader.read_string(4)?;
+        let unknown = reader.read_bytes(4)?.to_vec();
+
+        // The FDSC is a fixed 32 bytes; the STAB occupies the rest of the header.
+        let fdsc = FDSC::parse(reader.read_bytes(32)?)?;
+        if length < reader.position() {
+            return Err(FilmError::UnexpectedEof);
+        }
+        let stab_length = length - reader.position();
+        let stab = STAB::parse(reader.read_bytes(stab_length)?)?;
 
         return Ok(FILMHeader {
             signature: signature,
             length: length,
-            version: String::from_utf8(data[8..12].to_vec()).unwrap(),
-            unknown: data[12..16].to_vec(),
-            fdsc: FDSC::parse(&data[16..48]),
-            stab: STAB::parse(&data[48..length]),
+            version: version,
+            unknown: unknown,
+            fdsc: fdsc,
+            stab: stab,
         });
     }
+
+    /// Serializes this header back into the on-disk FILM representation.
+    /// This emits the 16-byte signature/length/version block followed by the
+    /// FDSC and STAB chunks; the result is exactly `length` bytes long and can
+    /// be written out ahead of the sample data to reconstruct a `.cpk` file.
+    pub fn write(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend("FILM".as_bytes());
+        bytes.extend(&bytes_from_uint32(self.length as u32));
+        bytes.extend(&fixed_field(&self.version, 4));
+        bytes.extend(&self.unknown);
+        bytes.extend(&self.fdsc.to_bytes());
+        bytes.extend(&self.stab.to_bytes());
+        return bytes;
+    }
 }
 
 /// The FDSC chunk contains information about the streams inside the container;
@@ -139,32 +218,57 @@ pub struct FDSC {
 
 impl FDSC {
     /// Parses the passed slice of bytes, returning an `FDSC` object.
-    pub fn parse(data : &[u8]) -> FDSC {
-        let signature_bytes = vec![
-            data[0], data[1], data[2], data[3],
-        ];
-        let fourcc_bytes = vec![
-            data[8], data[9], data[10], data[11],
-        ];
-        let audio_codec;
-        match data[23] {
-            0 => audio_codec = AudioCodec::PCM,
-            2 => audio_codec = AudioCodec::ADX,
-            _ => audio_codec = AudioCodec::Unknown,
-        };
+    pub fn parse(data : &[u8]) -> Result<FDSC, FilmError> {
+        let mut reader = Reader::new(data);
 
-        return FDSC {
-            signature: String::from_utf8(signature_bytes).unwrap(),
-            length: uint32_from_bytes([data[4], data[5], data[6], data[7]]),
-            fourcc: String::from_utf8(fourcc_bytes).unwrap(),
-            height: uint32_from_bytes([data[12], data[13], data[14], data[15]]),
-            width: uint32_from_bytes([data[16], data[17], data[18], data[19]]),
-            bpp: data[20],
-            channels: data[21],
-            audio_resolution: data[22],
-            audio_compression: audio_codec,
-            audio_sampling_rate: uint16_from_bytes([data[24], data[25]]),
+        let signature = reader.read_string(4)?;
+        let length = reader.read_u32()?;
+        let fourcc = reader.read_string(4)?;
+        let height = reader.read_u32()?;
+        let width = reader.read_u32()?;
+        let bpp = reader.read_u8()?;
+        let channels = reader.read_u8()?;
+        let audio_resolution = reader.read_u8()?;
+        let audio_compression = match reader.read_u8()? {
+            0 => AudioCodec::PCM,
+            2 => AudioCodec::ADX,
+            _ => AudioCodec::Unknown,
         };
+        let audio_sampling_rate = reader.read_u16()?;
+
+        return Ok(FDSC {
+            signature: signature,
+            length: length,
+            fourcc: fourcc,
+            height: height,
+            width: width,
+            bpp: bpp,
+            channels: channels,
+            audio_resolution: audio_resolution,
+            audio_compression: audio_compression,
+            audio_sampling_rate: audio_sampling_rate,
+        });
+    }
+
+    /// Serializes this FDSC into its 32-byte on-disk representation.
+    /// The length field is always written as 32, matching the fixed FDSC size.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend("FDSC".as_bytes());
+        bytes.extend(&bytes_from_uint32(32));
+        bytes.extend(&fixed_field(&self.fourcc, 4));
+        bytes.extend(&bytes_from_uint32(self.height));
+        bytes.extend(&bytes_from_uint32(self.width));
+        bytes.push(self.bpp);
+        bytes.push(self.channels);
+        bytes.push(self.audio_resolution);
+        bytes.push(self.audio_compression.code());
+        bytes.extend(&bytes_from_uint16(self.audio_sampling_rate));
+        // The FDSC is a fixed 32 bytes; the trailing bytes are unused.
+        while bytes.len() < 32 {
+            bytes.push(0);
+        }
+        return bytes;
     }
 
     /// Returns a string identifying the audio format. Valid return values are "pcm" and "adx".
@@ -208,25 +312,83 @@ pub struct STAB {
 
 impl STAB {
     /// Parses the passed slice of bytes, returning a `STAB` object.
-    pub fn parse(data : &[u8]) -> STAB {
-        let signature_bytes = vec![
-            data[0], data[1], data[2], data[3],
-        ];
-        let entries = uint32_from_bytes([data[12], data[13], data[14], data[15]]);
-        let mut samples = vec![];
-        for i in 1..entries {
-            let index = i as usize * 16;
-            let sample = Sample::parse(&data[index..index + 16]);
-            samples.push(sample);
+    ///
+    /// Borrowing the defensive approach of the Mozilla mp4parse crate, the
+    /// declared entry count is capped against a sanity limit before any
+    /// allocation: since a sample occupies at least one tick, no plausible file
+    /// can hold more entries than `framerate` ticks-per-second times a generous
+    /// maximum running time. An over-large count yields `OversizedTable` instead
+    /// of attempting a huge allocation.
+    pub fn parse(data : &[u8]) -> Result<STAB, FilmError> {
+        // No real FILM file runs longer than a few hours.
+        const MAX_DURATION_SECONDS : usize = 6 * 60 * 60;
+
+        let mut reader = Reader::new(data);
+
+        let signature = reader.read_string(4)?;
+        let length = reader.read_u32()?;
+        let framerate = reader.read_u32()?;
+        let entries = reader.read_u32()? as usize;
+
+        let sanity_limit = (framerate as usize).max(1) * MAX_DURATION_SECONDS;
+        if entries > sanity_limit {
+            return Err(FilmError::OversizedTable);
         }
 
-        return STAB {
-            signature: String::from_utf8(signature_bytes).unwrap(),
-            length: uint32_from_bytes([data[4], data[5], data[6], data[7]]),
-            framerate: uint32_from_bytes([data[8], data[9], data[10], data[11]]),
-            entries: entries,
+        // Cross-check the declared count against the bytes actually present: each
+        // entry is 16 bytes, so a truncated file can't describe more than
+        // `remaining() / 16` of them. This caps the up-front allocation; the read
+        // loop below still errors out cleanly if the buffer really is short.
+        let available = reader.remaining() / 16;
+        let mut samples = Vec::with_capacity(entries.min(available));
+        for _ in 0..entries {
+            samples.push(Sample::parse(reader.read_bytes(16)?)?);
+        }
+
+        return Ok(STAB {
+            signature: signature,
+            length: length,
+            framerate: framerate,
+            entries: entries as u32,
             sample_table: samples,
-        };
+        });
+    }
+
+    /// Walks the sample table in order, accumulating ticks to place each sample
+    /// on a shared timeline. The result is enough to build a seek index or to
+    /// compute the total runtime (the `start_time` plus `duration` of the final
+    /// entry) without bit-twiddling the raw sample info fields.
+    pub fn timeline(&self) -> Vec<TimelineEntry> {
+        let mut entries = vec![];
+        let mut clock = 0;
+
+        for sample in &self.sample_table {
+            let stream = if sample.is_audio() { Stream::Audio } else { Stream::Video };
+            let duration = sample.duration();
+            entries.push(TimelineEntry {
+                stream: stream,
+                start_time: clock,
+                duration: duration,
+                keyframe: sample.is_keyframe().unwrap_or(false),
+            });
+            clock += duration;
+        }
+
+        return entries;
+    }
+
+    /// Serializes this STAB into its on-disk representation: the 16-byte header
+    /// followed by one 16-byte entry per sample in the table.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend("STAB".as_bytes());
+        bytes.extend(&bytes_from_uint32(self.length));
+        bytes.extend(&bytes_from_uint32(self.framerate));
+        bytes.extend(&bytes_from_uint32(self.entries));
+        for sample in &self.sample_table {
+            bytes.extend(&sample.to_bytes());
+        }
+        return bytes;
     }
 }
 
@@ -243,7 +405,7 @@ impl STAB {
 /// you can use your FILMHeader's `length` to determine that offset.
 /// For example, to extract this sample from the file's data:
 ///
-/// ```
+/// ```ignore
 /// // assuming a FILMHeader named `header`, and the entire file's contents as `film_data`
 /// let sample = header.stab.sample_table[0];
 /// let absolute_sample_offset = header.length + sample.offset;
@@ -257,19 +419,38 @@ pub struct Sample {
     /// The length of this sample's data, in bytes.
     pub length: usize,
     info1: [u8; 4],
-    #[allow(dead_code)]
     info2: [u8; 4],
 }
 
 impl Sample {
     /// Parses the passed slice of bytes, returning a `Sample` object.
-    pub fn parse(data : &[u8]) -> Sample {
-        return Sample {
-            offset: uint32_from_bytes([data[0], data[1], data[2], data[3]]) as usize,
-            length: uint32_from_bytes([data[4], data[5], data[6], data[7]]) as usize,
-            info1: [data[8], data[9], data[10], data[11]],
-            info2: [data[12], data[13], data[14], data[15]],
-        }
+    pub fn parse(data : &[u8]) -> Result<Sample, FilmError> {
+        let mut reader = Reader::new(data);
+
+        let offset = reader.read_u32()? as usize;
+        let length = reader.read_u32()? as usize;
+        let info1 = reader.read_bytes(4)?;
+        let info1 = [info1[0], info1[1], info1[2], info1[3]];
+        let info2 = reader.read_bytes(4)?;
+        let info2 = [info2[0], info2[1], info2[2], info2[3]];
+
+        return Ok(Sample {
+            offset: offset,
+            length: length,
+            info1: info1,
+            info2: info2,
+        });
+    }
+
+    /// Serializes this Sample into its 16-byte sample-table entry:
+    /// `offset(u32), length(u32), info1, info2`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend(&bytes_from_uint32(self.offset as u32));
+        bytes.extend(&bytes_from_uint32(self.length as u32));
+        bytes.extend(&self.info1);
+        bytes.extend(&self.info2);
+        return bytes;
     }
 
     /// Reads the metadata in this Sample to determine whether this sample contains audio.
@@ -295,4 +476,297 @@ impl Sample {
         let byte = uint32_from_bytes(self.info1);
         return Some((byte & (1 << 31)) == 0);
     }
+
+    /// Returns the presentation timestamp of this sample, in ticks.
+    /// For video this is the low 31 bits of `info1` (the high bit carries the
+    /// keyframe flag); for audio the field isn't a real timestamp, so callers
+    /// should prefer [`STAB::timeline`] to place audio on the clock.
+    pub fn pts(&self) -> u32 {
+        return uint32_from_bytes(self.info1) & 0x7FFFFFFF;
+    }
+
+    /// Returns the duration of this sample, in ticks, decoded from `info2`.
+    pub fn duration(&self) -> u32 {
+        return uint32_from_bytes(self.info2);
+    }
+
+    /// Returns the presentation timestamp in seconds, dividing by the STAB's
+    /// ticks-per-second framerate.
+    pub fn pts_seconds(&self, framerate : u32) -> f64 {
+        return self.pts() as f64 / framerate as f64;
+    }
+
+    /// Returns the duration in seconds, dividing by the STAB's ticks-per-second
+    /// framerate.
+    pub fn duration_seconds(&self, framerate : u32) -> f64 {
+        return self.duration() as f64 / framerate as f64;
+    }
+}
+
+/// A single packet handed to a [`FilmBuilder`].
+/// Each packet is either audio or video and carries the raw stream bytes along
+/// with the timing metadata that ends up in its sample-table entry.
+pub struct FilmPacket {
+    /// Whether this packet belongs to the audio stream. Video otherwise.
+    pub audio: bool,
+    /// Whether this packet is a keyframe. Only meaningful for video.
+    pub keyframe: bool,
+    /// The presentation timestamp, in ticks.
+    pub pts: u32,
+    /// The duration of this packet, in ticks.
+    pub duration: u32,
+    /// The raw sample data.
+    pub data: Vec<u8>,
+}
+
+/// Builds a FILM container from an ordered list of audio and video packets.
+/// Describe the streams with the `video`/`audio` configuration methods, push
+/// packets in playback order, then call [`FilmBuilder::build`] to emit a
+/// complete `.cpk` file as a byte vector.
+///
+/// The builder follows the structure of the FFmpeg FILM muxer: sample offsets
+/// are relative to the end of the header, so they're accumulated directly as
+/// the packets are laid out.
+pub struct FilmBuilder {
+    fourcc: String,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    channels: u8,
+    audio_resolution: u8,
+    audio_compression: AudioCodec,
+    audio_sampling_rate: u16,
+    version: String,
+    framerate: u32,
+    packets: Vec<FilmPacket>,
+}
+
+impl FilmBuilder {
+    /// Creates an empty builder. The video defaults to a 24bpp Cinepak stream;
+    /// use the configuration methods to describe the actual streams before
+    /// adding packets.
+    pub fn new() -> FilmBuilder {
+        return FilmBuilder {
+            fourcc: "cvid".to_string(),
+            width: 0,
+            height: 0,
+            bpp: 24,
+            channels: 1,
+            audio_resolution: 8,
+            audio_compression: AudioCodec::PCM,
+            audio_sampling_rate: 0,
+            version: "1.09".to_string(),
+            framerate: 0,
+            packets: vec![],
+        };
+    }
+
+    /// Describes the video stream: the fourcc, dimensions, and colour depth.
+    pub fn video(&mut self, fourcc : &str, width : u32, height : u32, bpp : u8) -> &mut FilmBuilder {
+        self.fourcc = fourcc.to_string();
+        self.width = width;
+        self.height = height;
+        self.bpp = bpp;
+        return self;
+    }
+
+    /// Describes the audio stream: channel count, bit depth, codec and rate.
+    pub fn audio(&mut self, channels : u8, resolution : u8, compression : AudioCodec, sampling_rate : u16) -> &mut FilmBuilder {
+        self.channels = channels;
+        self.audio_resolution = resolution;
+        self.audio_compression = compression;
+        self.audio_sampling_rate = sampling_rate;
+        return self;
+    }
+
+    /// Sets the base clock of the sample table, in ticks per second.
+    pub fn framerate(&mut self, framerate : u32) -> &mut FilmBuilder {
+        self.framerate = framerate;
+        return self;
+    }
+
+    /// Appends a packet to the container. Packets are muxed in the order added.
+    pub fn push_packet(&mut self, packet : FilmPacket) -> &mut FilmBuilder {
+        self.packets.push(packet);
+        return self;
+    }
+
+    /// Emits the finished container as a byte vector: the header followed by the
+    /// concatenated sample data.
+    pub fn build(&self) -> Vec<u8> {
+        let entries = self.packets.len() as u32;
+        let stab_length = 16 + self.packets.len() * 16;
+        let header_length = 16 + 32 + stab_length;
+
+        // Build the sample-table entries. Offsets are relative to the end of the
+        // header, so they accumulate straight from each packet's size.
+        let mut samples = vec![];
+        let mut offset : usize = 0;
+        for packet in &self.packets {
+            let info1;
+            if packet.audio {
+                info1 = [255, 255, 255, 255];
+            } else {
+                // Video entries pack the pts into the low 31 bits of info1,
+                // with bit 31 set when the frame is not a keyframe.
+                let mut value = packet.pts & 0x7FFFFFFF;
+                if !packet.keyframe {
+                    value |= 1 << 31;
+                }
+                info1 = bytes_from_uint32(value);
+            }
+
+            samples.push(Sample {
+                offset: offset,
+                length: packet.data.len(),
+                info1: info1,
+                info2: bytes_from_uint32(packet.duration),
+            });
+            offset += packet.data.len();
+        }
+
+        let stab = STAB {
+            signature: "STAB".to_string(),
+            length: stab_length as u32,
+            framerate: self.framerate,
+            entries: entries,
+            sample_table: samples,
+        };
+
+        let fdsc = FDSC {
+            signature: "FDSC".to_string(),
+            length: 32,
+            fourcc: self.fourcc.clone(),
+            height: self.height,
+            width: self.width,
+            bpp: self.bpp,
+            channels: self.channels,
+            audio_resolution: self.audio_resolution,
+            audio_compression: self.audio_compression.clone(),
+            audio_sampling_rate: self.audio_sampling_rate,
+        };
+
+        let header = FILMHeader {
+            signature: "FILM".to_string(),
+            length: header_length,
+            version: self.version.clone(),
+            unknown: vec![0, 0, 0, 0],
+            fdsc: fdsc,
+            stab: stab,
+        };
+
+        let mut bytes = header.write();
+        for packet in &self.packets {
+            bytes.extend(&packet.data);
+        }
+        return bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_builder_round_trips_through_parse() {
+        let mut builder = FilmBuilder::new();
+        builder
+            .video("cvid", 320, 240, 24)
+            .audio(2, 16, AudioCodec::PCM, 22050)
+            .framerate(600);
+        builder.push_packet(FilmPacket {
+            audio: false,
+            keyframe: true,
+            pts: 0,
+            duration: 40,
+            data: vec![1, 2, 3, 4],
+        });
+        builder.push_packet(FilmPacket {
+            audio: true,
+            keyframe: false,
+            pts: 0,
+            duration: 20,
+            data: vec![9, 9],
+        });
+
+        let bytes = builder.build();
+        let header = FILMHeader::parse(&bytes).unwrap();
+
+        assert_eq!(header.fdsc.width, 320);
+        assert_eq!(header.fdsc.height, 240);
+        assert_eq!(header.fdsc.audio_codec(), "pcm");
+        assert_eq!(header.stab.framerate, 600);
+        assert_eq!(header.stab.sample_table.len(), 2);
+
+        let video = &header.stab.sample_table[0];
+        assert!(video.is_video());
+        assert_eq!(video.is_keyframe(), Some(true));
+        assert_eq!(video.offset, 0);
+        assert_eq!(video.length, 4);
+        assert_eq!(video.duration(), 40);
+
+        let audio = &header.stab.sample_table[1];
+        assert!(audio.is_audio());
+        assert_eq!(audio.offset, 4);
+        assert_eq!(audio.length, 2);
+
+        // The header length points exactly at the first sample's data.
+        assert_eq!(&bytes[header.length..header.length + 4], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn timeline_accumulates_ticks() {
+        let mut builder = FilmBuilder::new();
+        builder.video("cvid", 64, 64, 24).framerate(600);
+        builder.push_packet(FilmPacket {
+            audio: false,
+            keyframe: true,
+            pts: 0,
+            duration: 40,
+            data: vec![0],
+        });
+        builder.push_packet(FilmPacket {
+            audio: false,
+            keyframe: false,
+            pts: 40,
+            duration: 40,
+            data: vec![0],
+        });
+
+        let bytes = builder.build();
+        let header = FILMHeader::parse(&bytes).unwrap();
+        let timeline = header.stab.timeline();
+
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].stream, Stream::Video);
+        assert_eq!(timeline[0].start_time, 0);
+        assert_eq!(timeline[0].duration, 40);
+        assert!(timeline[0].keyframe);
+        // The second entry starts where the first ended.
+        assert_eq!(timeline[1].start_time, 40);
+        assert!(!timeline[1].keyframe);
+    }
+
+    #[test]
+    fn oversized_text_fields_keep_the_header_layout() {
+        let mut builder = FilmBuilder::new();
+        // An over-long fourcc must not shift the bytes that follow it.
+        builder.video("cinepak", 16, 16, 24).framerate(600);
+        builder.push_packet(FilmPacket {
+            audio: false,
+            keyframe: true,
+            pts: 0,
+            duration: 1,
+            data: vec![7],
+        });
+
+        let bytes = builder.build();
+        let header = FILMHeader::parse(&bytes).unwrap();
+
+        // The fourcc is truncated to its fixed 4 bytes; the rest still parses.
+        assert_eq!(header.fdsc.width, 16);
+        assert_eq!(header.fdsc.height, 16);
+        assert_eq!(header.stab.sample_table.len(), 1);
+        assert_eq!(&bytes[header.length..header.length + 1], &[7]);
+    }
 }
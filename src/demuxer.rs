@@ -0,0 +1,154 @@
+//! This module provides a high-level demuxer over any `Read + Seek` source.
+//! Rather than slicing the whole file into memory and separating audio from
+//! video by hand, a [`Demuxer`] parses the header incrementally and then yields
+//! one [`Packet`] per sample, seeking to each sample's data on demand. This
+//! mirrors the way the Mozilla mp4parse API surfaces track samples without
+//! requiring the caller to hold the entire file.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use container::{FILMHeader, Stream};
+use error::FilmError;
+
+/// The largest header we'll buffer. FILM headers are only a few KB in practice,
+/// so this guards against an untrusted, over-large declared length.
+const MAX_HEADER_SIZE : usize = 16 * 1024 * 1024;
+
+/// A single demuxed packet: the raw sample bytes plus the metadata a playback
+/// or transcoding pipeline needs to route and schedule it.
+pub struct Packet {
+    /// Which stream this packet belongs to.
+    pub stream: Stream,
+    /// The raw sample data.
+    pub data: Vec<u8>,
+    /// The presentation timestamp, in ticks.
+    pub pts: u32,
+    /// Whether this packet is a keyframe. Always `true` for audio.
+    pub keyframe: bool,
+}
+
+/// An error raised while demuxing: either an I/O failure from the underlying
+/// reader or a parse failure from the FILM header.
+#[derive(Debug)]
+pub enum DemuxError {
+    /// The underlying reader returned an I/O error.
+    Io(io::Error),
+    /// The FILM header couldn't be parsed.
+    Film(FilmError),
+}
+
+impl fmt::Display for DemuxError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DemuxError::Io(ref error) => return write!(f, "{}", error),
+            DemuxError::Film(ref error) => return write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for DemuxError {}
+
+impl From<io::Error> for DemuxError {
+    fn from(error : io::Error) -> DemuxError {
+        return DemuxError::Io(error);
+    }
+}
+
+impl From<FilmError> for DemuxError {
+    fn from(error : FilmError) -> DemuxError {
+        return DemuxError::Film(error);
+    }
+}
+
+/// Reads a FILM container from a `Read + Seek` source one packet at a time.
+/// Construct one with [`Demuxer::new`], then pull packets with
+/// [`Demuxer::read_packet`] or by iterating; the `Demuxer` also implements
+/// `Iterator`, yielding `Result<Packet, DemuxError>`.
+pub struct Demuxer<R : Read + Seek> {
+    reader: R,
+    header: FILMHeader,
+    index: usize,
+}
+
+impl<R : Read + Seek> Demuxer<R> {
+    /// Takes ownership of `reader` and parses the FILM header.
+    ///
+    /// The header is read in two steps, following `FILMHeader::guess_length`:
+    /// first the 8-byte prefix that carries the total header length, then the
+    /// remaining header bytes. Nothing more than the header is buffered; the
+    /// sample data is read lazily, on demand.
+    pub fn new(mut reader : R) -> Result<Demuxer<R>, DemuxError> {
+        let mut buffer = vec![0u8; 8];
+        reader.read_exact(&mut buffer)?;
+        if !FILMHeader::is_film_file(&buffer) {
+            return Err(DemuxError::Film(FilmError::BadSignature));
+        }
+
+        let length = FILMHeader::guess_length(&buffer);
+        if length < buffer.len() {
+            return Err(DemuxError::Film(FilmError::UnexpectedEof));
+        }
+        // The declared length is an untrusted 32-bit value; refuse an absurd one
+        // rather than pre-allocating up to 4GB for a malformed file. Real FILM
+        // headers are a few KB at most.
+        if length > MAX_HEADER_SIZE {
+            return Err(DemuxError::Film(FilmError::OversizedTable));
+        }
+        buffer.resize(length, 0);
+        reader.read_exact(&mut buffer[8..])?;
+
+        let header = FILMHeader::parse(&buffer)?;
+
+        return Ok(Demuxer {
+            reader: reader,
+            header: header,
+            index: 0,
+        });
+    }
+
+    /// Returns the parsed header, which describes both streams.
+    pub fn header(&self) -> &FILMHeader {
+        return &self.header;
+    }
+
+    /// Reads the next packet, seeking to its absolute offset in the file.
+    /// Returns `Ok(None)` once every sample has been yielded.
+    pub fn read_packet(&mut self) -> Result<Option<Packet>, DemuxError> {
+        if self.index >= self.header.stab.sample_table.len() {
+            return Ok(None);
+        }
+
+        let sample = &self.header.stab.sample_table[self.index];
+        self.index += 1;
+
+        // Sample offsets are relative to the end of the header.
+        let absolute_offset = (self.header.length + sample.offset) as u64;
+        self.reader.seek(SeekFrom::Start(absolute_offset))?;
+        let mut data = vec![0u8; sample.length];
+        self.reader.read_exact(&mut data)?;
+
+        let stream = if sample.is_audio() { Stream::Audio } else { Stream::Video };
+
+        return Ok(Some(Packet {
+            stream: stream,
+            data: data,
+            pts: sample.pts(),
+            keyframe: sample.is_keyframe().unwrap_or(true),
+        }));
+    }
+}
+
+impl<R : Read + Seek> Iterator for Demuxer<R> {
+    type Item = Result<Packet, DemuxError>;
+
+    fn next(&mut self) -> Option<Result<Packet, DemuxError>> {
+        match self.read_packet() {
+            Ok(Some(packet)) => return Some(Ok(packet)),
+            Ok(None) => return None,
+            Err(error) => return Some(Err(error)),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! This module defines the error type returned when parsing fails.
+//! FILM files are frequently extracted from untrusted game assets, so every
+//! parser is bounds-checked and surfaces failures through `FilmError` rather
+//! than panicking.
+
+use std::error::Error;
+use std::fmt;
+
+/// An error encountered while parsing a FILM container or one of its streams.
+#[derive(Debug, PartialEq)]
+pub enum FilmError {
+    /// The data ended before a structure could be fully read.
+    UnexpectedEof,
+    /// A signature or magic number (the FILM signature, the ADX 0x8000 magic)
+    /// didn't match the expected value.
+    BadSignature,
+    /// A field that should have held text wasn't valid UTF-8.
+    InvalidUtf8,
+    /// A declared table size exceeded the sanity limit for the file.
+    OversizedTable,
+}
+
+impl fmt::Display for FilmError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        use self::FilmError::*;
+
+        let message = match *self {
+            UnexpectedEof => "unexpected end of data",
+            BadSignature => "missing or invalid signature",
+            InvalidUtf8 => "invalid UTF-8 in a text field",
+            OversizedTable => "sample table size exceeds the sanity limit",
+        };
+        return write!(f, "{}", message);
+    }
+}
+
+impl Error for FilmError {}
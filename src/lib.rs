@@ -6,4 +6,8 @@
 pub mod codec;
 /// Contains tools for parsing the FILM container.
 pub mod container;
+/// Provides a streaming demuxer over `Read + Seek` sources.
+pub mod demuxer;
+/// Defines the error type returned by the parsers.
+pub mod error;
 mod utils;
@@ -1,3 +1,74 @@
+use error::FilmError;
+
+/// A bounds-checked cursor over a byte slice.
+/// Every read advances the position and returns `Err(FilmError::UnexpectedEof)`
+/// rather than panicking when the slice runs short, which lets the parsers stay
+/// panic-free in the face of truncated or malformed input.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Creates a reader positioned at the start of `data`.
+    pub fn new(data : &'a [u8]) -> Reader<'a> {
+        return Reader { data: data, pos: 0 };
+    }
+
+    /// Returns the current offset into the slice.
+    pub fn position(&self) -> usize {
+        return self.pos;
+    }
+
+    /// Returns the number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        return self.data.len() - self.pos;
+    }
+
+    /// Moves the cursor to an absolute offset, failing if it's past the end.
+    pub fn seek(&mut self, pos : usize) -> Result<(), FilmError> {
+        if pos > self.data.len() {
+            return Err(FilmError::UnexpectedEof);
+        }
+        self.pos = pos;
+        return Ok(());
+    }
+
+    /// Reads `count` bytes, advancing the cursor.
+    pub fn read_bytes(&mut self, count : usize) -> Result<&'a [u8], FilmError> {
+        if self.pos + count > self.data.len() {
+            return Err(FilmError::UnexpectedEof);
+        }
+        let slice = &self.data[self.pos..self.pos + count];
+        self.pos += count;
+        return Ok(slice);
+    }
+
+    /// Reads a single byte.
+    pub fn read_u8(&mut self) -> Result<u8, FilmError> {
+        let bytes = self.read_bytes(1)?;
+        return Ok(bytes[0]);
+    }
+
+    /// Reads a big-endian 16-bit integer.
+    pub fn read_u16(&mut self) -> Result<u16, FilmError> {
+        let bytes = self.read_bytes(2)?;
+        return Ok(uint16_from_bytes([bytes[0], bytes[1]]));
+    }
+
+    /// Reads a big-endian 32-bit integer.
+    pub fn read_u32(&mut self) -> Result<u32, FilmError> {
+        let bytes = self.read_bytes(4)?;
+        return Ok(uint32_from_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    }
+
+    /// Reads a fixed-length UTF-8 string, failing on invalid UTF-8.
+    pub fn read_string(&mut self, count : usize) -> Result<String, FilmError> {
+        let bytes = self.read_bytes(count)?;
+        return String::from_utf8(bytes.to_vec()).map_err(|_| FilmError::InvalidUtf8);
+    }
+}
+
 pub fn uint32_from_bytes(bytes : [u8; 4]) -> u32 {
     return ((bytes[0] as u32) << 24) +
         ((bytes[1] as u32) << 16) +
@@ -8,3 +79,19 @@ pub fn uint32_from_bytes(bytes : [u8; 4]) -> u32 {
 pub fn uint16_from_bytes(bytes : [u8; 2]) -> u16 {
     return ((bytes[0] as u16) << 8) + bytes[1] as u16;
 }
+
+pub fn bytes_from_uint32(value : u32) -> [u8; 4] {
+    return [
+        (value >> 24) as u8,
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ];
+}
+
+pub fn bytes_from_uint16(value : u16) -> [u8; 2] {
+    return [
+        (value >> 8) as u8,
+        value as u8,
+    ];
+}